@@ -1,28 +1,741 @@
 use nih_plug::prelude::*;
-use std::sync::Arc;
+use nih_plug_egui::{create_egui_editor, egui, widgets, EguiState};
+use std::f32::consts;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
 
 // This is a shortened version of the gain example with most comments removed, check out
 // https://github.com/robbert-vdh/nih-plug/blob/master/plugins/examples/gain/src/lib.rs to get
 // started
 
+/// How many taps each polyphase subfilter gets. The full prototype low-pass is
+/// `TAPS_PER_PHASE * factor` taps long, which keeps us in the 64–128 tap range
+/// the design calls for at 4x/8x.
+const TAPS_PER_PHASE: usize = 16;
+/// The largest oversampling factor [`OversamplingFactor`] can select. The FIR
+/// delay lines are sized for this so switching factors never reallocates.
+const MAX_OVERSAMPLING: usize = 8;
+/// Kaiser window beta. ~8.0 gives roughly 80 dB of stopband attenuation, which
+/// is plenty to keep the imaging/aliasing products from the interpolation and
+/// decimation stages below the noise floor.
+const KAISER_BETA: f32 = 8.0;
+/// Number of bands the signal is split into by the [`Crossover`] before crushing. Two crossover
+/// frequencies give three bands: low, mid and high.
+const NUM_BANDS: usize = 3;
+/// Length of the [`Scope`] ring buffer feeding the editor's scrolling display.
+const SCOPE_SIZE: usize = 512;
+/// Size of the per-channel dry delay line used for latency-compensated dry/wet mixing. Has to be
+/// larger than the worst-case oversampling latency.
+const MAX_DRY_DELAY: usize = 32;
+/// Pole for the one-pole DC blocker applied after the waveshaper.
+const DC_BLOCKER_R: f32 = 0.995;
+
 struct Dontpanic {
     params: Arc<DontpanicParams>,
+
+    /// One oversampler per channel per band, rebuilt in [`Plugin::initialize`]. Each band crushes
+    /// independently, so each needs its own anti-aliasing filter state.
+    oversamplers: Vec<[Oversampler; NUM_BANDS]>,
+
+    /// Per-channel Linkwitz-Riley band splitter, rebuilt in [`Plugin::initialize`].
+    crossovers: Vec<Crossover>,
+
+    /// Per-channel sample-and-hold state for the `downsample` decimator. `sh_phase` accumulates
+    /// `1.0 / downsample` each sample and latches a new `sh_held` value whenever it wraps past an
+    /// integer boundary.
+    sh_held: Vec<f32>,
+    sh_phase: Vec<f32>,
+
+    /// Recent input/output samples shared with the editor's scope. Cloned into the editor closure.
+    scope: Arc<Scope>,
+
+    /// Notes currently held, newest last. Used to drive both MIDI modes.
+    held_notes: Vec<u8>,
+    /// Per-channel AR envelope for the gate mode, kept per channel so the smoothing avoids clicks.
+    gate_env: Vec<f32>,
+    /// One-pole attack/release coefficients for `gate_env`, derived from the sample rate.
+    gate_attack: f32,
+    gate_release: f32,
+
+    /// Per-channel latency-compensation delay for the dry signal feeding the dry/wet mix.
+    dry_delays: Vec<DelayLine>,
+    /// Per-channel DC blocker applied after the waveshaper.
+    dc_blockers: Vec<DcBlocker>,
 }
 
 #[derive(Params)]
 struct DontpanicParams {
-    /// The parameter's ID is used to identify the parameter in the wrappred plugin API. As long as
-    /// these IDs remain constant, you can rename and reorder these fields as you wish. The
-    /// parameters are exposed to the host in the same order they were defined. In this case, this
-    /// gain parameter is stored as linear gain while the values are displayed in decibels.
+    /// Editor window state, persisted as part of the plugin state so the window size is restored.
+    #[persist = "editor-state"]
+    pub editor_state: Arc<EguiState>,
+
+    /// Crush amount for the mid band (between the low and high crossovers). Despite the name this
+    /// is a quantisation grid density, not a bit depth in the literal sense: the signal is
+    /// multiplied by this value, rounded to the nearest integer, then divided back down.
     #[id = "gain"]
-    pub crush: FloatParam,
+    pub bits: FloatParam,
+
+    /// Crush amount for the low band (below the low crossover).
+    #[id = "crush_low"]
+    pub crush_low: FloatParam,
+
+    /// Crush amount for the high band (above the high crossover).
+    #[id = "crush_high"]
+    pub crush_high: FloatParam,
+
+    /// Low/mid crossover frequency.
+    #[id = "xover_low"]
+    pub crossover_low: FloatParam,
+
+    /// Mid/high crossover frequency.
+    #[id = "xover_high"]
+    pub crossover_high: FloatParam,
+
+    /// Sample-rate reduction as a sample-and-hold divisor: 1.0 passes every sample through, larger
+    /// values hold each processed sample for longer and decimate the effective rate.
+    #[id = "downsample"]
+    pub downsample: FloatParam,
+
+    /// How incoming MIDI notes affect the effect.
+    #[id = "midi_mode"]
+    pub midi_mode: EnumParam<MidiMode>,
+
+    /// When key tracking, flips the direction so higher notes crush coarser instead of finer.
+    #[id = "key_track_invert"]
+    pub key_track_invert: BoolParam,
+
+    /// Output waveshaper applied after the quantiser.
+    #[id = "shape"]
+    pub shape: EnumParam<Waveshape>,
+
+    /// Dry/wet balance, 0% fully dry (latency-compensated) to 100% fully processed.
+    #[id = "mix"]
+    pub mix: FloatParam,
+
+    /// Oversampling factor for the crush + round + tanh block. Higher factors trade CPU for a
+    /// cleaner result by pushing the aliasing products from the hard nonlinearities out of the
+    /// audible band before they can fold back in.
+    #[id = "oversampling"]
+    pub oversampling: EnumParam<OversamplingFactor>,
+}
+
+/// Oversampling ratios exposed to the host.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+enum OversamplingFactor {
+    #[id = "1x"]
+    #[name = "1x"]
+    X1,
+    #[id = "2x"]
+    #[name = "2x"]
+    X2,
+    #[id = "4x"]
+    #[name = "4x"]
+    X4,
+    #[id = "8x"]
+    #[name = "8x"]
+    X8,
+}
+
+/// What incoming MIDI notes do to the effect.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+enum MidiMode {
+    /// MIDI is ignored.
+    #[id = "off"]
+    #[name = "Off"]
+    Off,
+    /// The most recently played note's pitch scales the crush amounts.
+    #[id = "key_track"]
+    #[name = "Key Track"]
+    KeyTrack,
+    /// The crushed signal is only passed while a note is held, gated with a short AR envelope.
+    #[id = "gate"]
+    #[name = "Gate"]
+    Gate,
+}
+
+/// Output waveshaper applied after the quantiser.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Waveshape {
+    #[id = "tanh"]
+    #[name = "Tanh"]
+    Tanh,
+    #[id = "hard"]
+    #[name = "Hard Clip"]
+    HardClip,
+    #[id = "rectify"]
+    #[name = "Rectify"]
+    Rectify,
+    #[id = "fold"]
+    #[name = "Asym Fold"]
+    AsymFold,
+    #[id = "bypass"]
+    #[name = "Bypass"]
+    Bypass,
+}
+
+impl OversamplingFactor {
+    /// The integer oversampling factor this variant represents.
+    fn factor(self) -> usize {
+        match self {
+            OversamplingFactor::X1 => 1,
+            OversamplingFactor::X2 => 2,
+            OversamplingFactor::X4 => 4,
+            OversamplingFactor::X8 => 8,
+        }
+    }
+}
+
+/// The interpolation/decimation prototype filters for one [`OversamplingFactor`], shared (via
+/// `Arc`) between every [`Oversampler`] instance currently using that factor.
+struct OversamplingFilters {
+    /// Interpolation prototype, `TAPS_PER_PHASE * factor` taps, scaled by `factor` to make up for
+    /// the energy lost to zero-stuffing.
+    up_coeffs: Arc<Vec<f32>>,
+    /// Decimation prototype, unity DC gain.
+    down_coeffs: Arc<Vec<f32>>,
+}
+
+/// Design the prototype filters for every [`OversamplingFactor`] once. Called lazily from
+/// [`Plugin::initialize`] (and memoized for any calls that race it), so [`Oversampler::set_factor`]
+/// only ever has to pick a pre-built set on the audio thread instead of designing one.
+fn oversampling_filters() -> &'static [OversamplingFilters; 4] {
+    static FILTERS: OnceLock<[OversamplingFilters; 4]> = OnceLock::new();
+    FILTERS.get_or_init(|| {
+        [
+            OversamplingFactor::X1,
+            OversamplingFactor::X2,
+            OversamplingFactor::X4,
+            OversamplingFactor::X8,
+        ]
+        .map(|factor| {
+            let factor = factor.factor();
+            if factor <= 1 {
+                return OversamplingFilters {
+                    up_coeffs: Arc::new(Vec::new()),
+                    down_coeffs: Arc::new(Vec::new()),
+                };
+            }
+
+            let num_taps = TAPS_PER_PHASE * factor;
+            // Cut off at the original Nyquist, i.e. half of the pre-oversampling rate expressed as
+            // a fraction of the oversampled rate.
+            let cutoff = 0.5 / factor as f32;
+
+            let down_coeffs = design_lowpass(num_taps, cutoff, KAISER_BETA);
+            let mut up_coeffs = down_coeffs.clone();
+            for tap in &mut up_coeffs {
+                *tap *= factor as f32;
+            }
+
+            OversamplingFilters {
+                up_coeffs: Arc::new(up_coeffs),
+                down_coeffs: Arc::new(down_coeffs),
+            }
+        })
+    })
+}
+
+/// A per-channel polyphase oversampler: zero-stuffing interpolation followed by a windowed-sinc
+/// low-pass to upsample, and the same low-pass followed by decimation to come back down. The
+/// prototype filters are precomputed once by [`oversampling_filters`] and shared by reference; the
+/// FIR delay lines are sized once for [`MAX_OVERSAMPLING`]. Switching factors just swaps which
+/// `Arc` the coefficients point at, so the audio thread never allocates.
+struct Oversampler {
+    factor: usize,
+
+    /// Interpolation prototype, `TAPS_PER_PHASE * factor` taps, scaled by `factor` to make up for
+    /// the energy lost to zero-stuffing.
+    up_coeffs: Arc<Vec<f32>>,
+    /// Decimation prototype, unity DC gain.
+    down_coeffs: Arc<Vec<f32>>,
+
+    /// Host-rate input delay line for the interpolator, newest sample first.
+    up_delay: Vec<f32>,
+    up_pos: usize,
+    /// Oversampled-rate delay line for the decimator, newest sample first.
+    down_delay: Vec<f32>,
+    down_pos: usize,
+}
+
+impl Oversampler {
+    fn new() -> Self {
+        let mut oversampler = Self {
+            factor: 1,
+            up_coeffs: Arc::new(Vec::new()),
+            down_coeffs: Arc::new(Vec::new()),
+            up_delay: vec![0.0; TAPS_PER_PHASE],
+            up_pos: 0,
+            down_delay: vec![0.0; TAPS_PER_PHASE * MAX_OVERSAMPLING],
+            down_pos: 0,
+        };
+        oversampler.set_factor(1);
+        oversampler
+    }
+
+    /// Switch to the precomputed prototype filters for `factor`. Just an `Arc` clone (a refcount
+    /// bump, not an allocation), so this is safe to call every block when the user is dragging the
+    /// selector.
+    fn set_factor(&mut self, factor: usize) {
+        if factor == self.factor {
+            return;
+        }
+
+        self.factor = factor;
+        let index = match factor {
+            2 => 1,
+            4 => 2,
+            8 => 3,
+            _ => 0,
+        };
+        let filters = &oversampling_filters()[index];
+        self.up_coeffs = filters.up_coeffs.clone();
+        self.down_coeffs = filters.down_coeffs.clone();
+    }
+
+    /// Combined group delay of the interpolation and decimation FIRs, in host-rate samples. Zero
+    /// at 1x where the filters are bypassed.
+    fn latency_samples(&self) -> usize {
+        if self.factor <= 1 {
+            0
+        } else {
+            // Each linear-phase FIR has a group delay of (len - 1) / 2 oversampled samples; two of
+            // them, converted back to the host rate.
+            (self.down_coeffs.len() - 1) / self.factor
+        }
+    }
+
+    /// Zero both delay lines. Called from [`Plugin::reset`].
+    fn reset(&mut self) {
+        self.up_delay.iter_mut().for_each(|s| *s = 0.0);
+        self.down_delay.iter_mut().for_each(|s| *s = 0.0);
+        self.up_pos = 0;
+        self.down_pos = 0;
+    }
+
+    #[inline]
+    fn up_push(&mut self, x: f32) {
+        self.up_pos = (self.up_pos + self.up_delay.len() - 1) % self.up_delay.len();
+        self.up_delay[self.up_pos] = x;
+    }
+
+    #[inline]
+    fn up_at(&self, k: usize) -> f32 {
+        self.up_delay[(self.up_pos + k) % self.up_delay.len()]
+    }
+
+    #[inline]
+    fn down_push(&mut self, x: f32) {
+        self.down_pos = (self.down_pos + self.down_delay.len() - 1) % self.down_delay.len();
+        self.down_delay[self.down_pos] = x;
+    }
+
+    #[inline]
+    fn down_at(&self, j: usize) -> f32 {
+        self.down_delay[(self.down_pos + j) % self.down_delay.len()]
+    }
+
+    /// Run one host-rate sample through `shape` at the oversampled rate. At 1x this is just
+    /// `shape(x)`; otherwise we interpolate to `factor` samples, apply `shape` to each, and
+    /// decimate back down to a single output sample.
+    fn process_sample(&mut self, x: f32, mut shape: impl FnMut(f32) -> f32) -> f32 {
+        let n = self.factor;
+        if n <= 1 {
+            return shape(x);
+        }
+
+        self.up_push(x);
+        for p in 0..n {
+            // Polyphase interpolation: subfilter `p` produces the `p`th oversampled sample.
+            let mut acc = 0.0;
+            for k in 0..TAPS_PER_PHASE {
+                acc += self.up_coeffs[k * n + p] * self.up_at(k);
+            }
+
+            let y = shape(acc);
+            self.down_push(y);
+        }
+
+        // We pushed exactly `n` oversampled samples, so a single decimated output is ready.
+        let mut out = 0.0;
+        for j in 0..self.down_coeffs.len() {
+            out += self.down_coeffs[j] * self.down_at(j);
+        }
+        out
+    }
+}
+
+/// Design a windowed-sinc low-pass with `num_taps` taps and the given normalised `cutoff`
+/// (fraction of the sample rate), windowed with a Kaiser window of the given `beta`. The result is
+/// normalised to unity DC gain.
+fn design_lowpass(num_taps: usize, cutoff: f32, beta: f32) -> Vec<f32> {
+    let mut coeffs = vec![0.0f32; num_taps];
+    let center = (num_taps - 1) as f32 / 2.0;
+    let denom = bessel_i0(beta);
+
+    let mut sum = 0.0;
+    for (i, tap) in coeffs.iter_mut().enumerate() {
+        let n = i as f32 - center;
+
+        // Ideal low-pass impulse response.
+        let sinc = if n.abs() < 1e-6 {
+            2.0 * cutoff
+        } else {
+            (consts::TAU * cutoff * n).sin() / (consts::PI * n)
+        };
+
+        // Kaiser window.
+        let ratio = (i as f32 - center) / center;
+        let window = bessel_i0(beta * (1.0 - ratio * ratio).max(0.0).sqrt()) / denom;
+
+        *tap = sinc * window;
+        sum += *tap;
+    }
+
+    for tap in &mut coeffs {
+        *tap /= sum;
+    }
+    coeffs
+}
+
+/// Zeroth-order modified Bessel function of the first kind, used by the Kaiser window.
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x_sq = (x / 2.0) * (x / 2.0);
+    for k in 1..32 {
+        term *= half_x_sq / (k * k) as f32;
+        sum += term;
+        if term < 1e-9 * sum {
+            break;
+        }
+    }
+    sum
+}
+
+/// A transposed direct-form II biquad. Two of these cascaded make one 4th-order Linkwitz-Riley
+/// crossover slope.
+#[derive(Clone, Copy, Default)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    #[inline]
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+
+    fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+
+    /// RBJ-cookbook Butterworth (Q = 1/√2) low-pass coefficients for the given cutoff.
+    fn lowpass(sample_rate: f32, cutoff: f32) -> Self {
+        let (cos_w0, alpha) = Self::prototype(sample_rate, cutoff);
+        let a0 = 1.0 + alpha;
+        let b1 = 1.0 - cos_w0;
+        Self {
+            b0: (b1 / 2.0) / a0,
+            b1: b1 / a0,
+            b2: (b1 / 2.0) / a0,
+            a1: (-2.0 * cos_w0) / a0,
+            a2: (1.0 - alpha) / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    /// RBJ-cookbook Butterworth (Q = 1/√2) high-pass coefficients for the given cutoff.
+    fn highpass(sample_rate: f32, cutoff: f32) -> Self {
+        let (cos_w0, alpha) = Self::prototype(sample_rate, cutoff);
+        let a0 = 1.0 + alpha;
+        let b1 = 1.0 + cos_w0;
+        Self {
+            b0: (b1 / 2.0) / a0,
+            b1: -b1 / a0,
+            b2: (b1 / 2.0) / a0,
+            a1: (-2.0 * cos_w0) / a0,
+            a2: (1.0 - alpha) / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    fn prototype(sample_rate: f32, cutoff: f32) -> (f32, f32) {
+        let w0 = consts::TAU * (cutoff / sample_rate).clamp(1e-4, 0.49);
+        let cos_w0 = w0.cos();
+        let alpha = w0.sin() / consts::SQRT_2; // Q = 1/√2
+        (cos_w0, alpha)
+    }
+}
+
+/// Per-channel 3-band splitter built from cascaded 4th-order Linkwitz-Riley crossovers. The signal
+/// is first split at the low crossover into a low band and a remainder, and the remainder is split
+/// again at the high crossover into the mid and high bands. Each LR slope is two cascaded
+/// Butterworth biquads.
+#[derive(Default)]
+struct Crossover {
+    sample_rate: f32,
+    low_freq: f32,
+    high_freq: f32,
+
+    lp_low: [Biquad; 2],
+    hp_low: [Biquad; 2],
+    lp_high: [Biquad; 2],
+    hp_high: [Biquad; 2],
+}
+
+impl Crossover {
+    /// (Re)compute the biquad coefficients for the given crossover frequencies, keeping the filter
+    /// memory intact. Cheap enough to call per block while the frequencies are being automated.
+    /// `high_freq` is clamped to `low_freq` so an inverted pair (e.g. from independently automating
+    /// both params) can't turn `split`'s low/mid/high cascade into a nonsensical split.
+    fn set_frequencies(&mut self, low_freq: f32, high_freq: f32) {
+        let high_freq = high_freq.max(low_freq);
+        if low_freq == self.low_freq && high_freq == self.high_freq {
+            return;
+        }
+        self.low_freq = low_freq;
+        self.high_freq = high_freq;
+
+        let lp_low = Biquad::lowpass(self.sample_rate, low_freq);
+        let hp_low = Biquad::highpass(self.sample_rate, low_freq);
+        let lp_high = Biquad::lowpass(self.sample_rate, high_freq);
+        let hp_high = Biquad::highpass(self.sample_rate, high_freq);
+
+        for stage in &mut self.lp_low {
+            *stage = Biquad { z1: stage.z1, z2: stage.z2, ..lp_low };
+        }
+        for stage in &mut self.hp_low {
+            *stage = Biquad { z1: stage.z1, z2: stage.z2, ..hp_low };
+        }
+        for stage in &mut self.lp_high {
+            *stage = Biquad { z1: stage.z1, z2: stage.z2, ..lp_high };
+        }
+        for stage in &mut self.hp_high {
+            *stage = Biquad { z1: stage.z1, z2: stage.z2, ..hp_high };
+        }
+    }
+
+    fn reset(&mut self) {
+        for stage in self
+            .lp_low
+            .iter_mut()
+            .chain(&mut self.hp_low)
+            .chain(&mut self.lp_high)
+            .chain(&mut self.hp_high)
+        {
+            stage.reset();
+        }
+    }
+
+    /// Split one sample into `[low, mid, high]`.
+    #[inline]
+    fn split(&mut self, x: f32) -> [f32; NUM_BANDS] {
+        let low = self.lp_low[1].process(self.lp_low[0].process(x));
+        let rest = self.hp_low[1].process(self.hp_low[0].process(x));
+        let mid = self.lp_high[1].process(self.lp_high[0].process(rest));
+        let high = self.hp_high[1].process(self.hp_high[0].process(rest));
+        [low, mid, high]
+    }
+}
+
+/// The core crush character: quantise to a coarse grid, then run the selected output waveshaper.
+/// `amount` is the quantisation grid density (the old `crush`/`bits` value).
+#[inline]
+fn crush_shape(x: f32, amount: f32, shape: Waveshape) -> f32 {
+    let q = (x * amount).round() / amount;
+
+    match shape {
+        Waveshape::Tanh => q.tanh(),
+        Waveshape::HardClip => q.clamp(-1.0, 1.0),
+        Waveshape::Rectify => q.abs(),
+        // A biased sine wavefolder: roughly linear near zero, folding (and asymmetric thanks to
+        // the bias) as the level grows.
+        Waveshape::AsymFold => ((q + 0.25) * consts::FRAC_PI_2).sin(),
+        Waveshape::Bypass => q,
+    }
+}
+
+/// A fixed-length per-channel delay line used to latency-compensate the dry signal before mixing.
+struct DelayLine {
+    buf: Vec<f32>,
+    pos: usize,
+}
+
+impl DelayLine {
+    fn new(len: usize) -> Self {
+        Self {
+            buf: vec![0.0; len],
+            pos: 0,
+        }
+    }
+
+    /// Write `x` and return the sample `delay` samples ago.
+    #[inline]
+    fn push_pop(&mut self, x: f32, delay: usize) -> f32 {
+        let len = self.buf.len();
+        self.buf[self.pos] = x;
+        let out = self.buf[(self.pos + len - delay % len) % len];
+        self.pos = (self.pos + 1) % len;
+        out
+    }
+
+    fn reset(&mut self) {
+        self.buf.iter_mut().for_each(|s| *s = 0.0);
+        self.pos = 0;
+    }
+}
+
+/// A one-pole DC blocker: `y[n] = x[n] - x[n-1] + R * y[n-1]`.
+#[derive(Default)]
+struct DcBlocker {
+    x1: f32,
+    y1: f32,
+}
+
+impl DcBlocker {
+    #[inline]
+    fn process(&mut self, x: f32) -> f32 {
+        let y = x - self.x1 + DC_BLOCKER_R * self.y1;
+        self.x1 = x;
+        self.y1 = y;
+        y
+    }
+
+    fn reset(&mut self) {
+        self.x1 = 0.0;
+        self.y1 = 0.0;
+    }
+}
+
+/// One-pole smoothing coefficient for a given time constant in seconds.
+fn one_pole_coeff(time_secs: f32, sample_rate: f32) -> f32 {
+    if time_secs <= 0.0 {
+        return 1.0;
+    }
+    1.0 - (-1.0 / (time_secs * sample_rate)).exp()
+}
+
+/// A tiny lock-free ring buffer of recent input/output sample pairs. The audio thread is the sole
+/// writer and the editor is the sole reader, so plain relaxed atomics are enough to shuttle the
+/// scope data across without locking.
+struct Scope {
+    input: Vec<AtomicU32>,
+    output: Vec<AtomicU32>,
+    write_index: AtomicUsize,
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Self {
+            input: (0..SCOPE_SIZE).map(|_| AtomicU32::new(0)).collect(),
+            output: (0..SCOPE_SIZE).map(|_| AtomicU32::new(0)).collect(),
+            write_index: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Scope {
+    /// Push one input/output pair from the audio thread.
+    fn push(&self, input: f32, output: f32) {
+        let i = self.write_index.load(Ordering::Relaxed);
+        self.input[i].store(input.to_bits(), Ordering::Relaxed);
+        self.output[i].store(output.to_bits(), Ordering::Relaxed);
+        self.write_index
+            .store((i + 1) % SCOPE_SIZE, Ordering::Relaxed);
+    }
+
+    /// Copy the buffer out in write order (oldest first) for the editor.
+    fn snapshot(&self) -> (Vec<f32>, Vec<f32>) {
+        let start = self.write_index.load(Ordering::Relaxed);
+        let mut input = Vec::with_capacity(SCOPE_SIZE);
+        let mut output = Vec::with_capacity(SCOPE_SIZE);
+        for offset in 0..SCOPE_SIZE {
+            let i = (start + offset) % SCOPE_SIZE;
+            input.push(f32::from_bits(self.input[i].load(Ordering::Relaxed)));
+            output.push(f32::from_bits(self.output[i].load(Ordering::Relaxed)));
+        }
+        (input, output)
+    }
+}
+
+/// Draw a line through `points` (already mapped into screen space) on `painter`.
+fn draw_line(painter: &egui::Painter, points: Vec<egui::Pos2>, color: egui::Color32) {
+    painter.add(egui::Shape::line(points, egui::Stroke::new(1.0, color)));
+}
+
+/// Plot the quantise + waveshaper transfer curve for the current mid-band crush amount and shape
+/// so users can see what the crush is doing to the signal.
+fn draw_transfer_curve(ui: &mut egui::Ui, amount: f32, shape: Waveshape) {
+    let (response, painter) =
+        ui.allocate_painter(egui::vec2(ui.available_width(), 120.0), egui::Sense::hover());
+    let rect = response.rect;
+
+    let points = (0..=128)
+        .map(|i| {
+            let x = -1.0 + 2.0 * i as f32 / 128.0;
+            let y = crush_shape(x, amount, shape);
+            egui::pos2(
+                rect.left() + (x * 0.5 + 0.5) * rect.width(),
+                rect.center().y - y.clamp(-1.0, 1.0) * rect.height() / 2.0,
+            )
+        })
+        .collect();
+    draw_line(&painter, points, egui::Color32::LIGHT_BLUE);
+}
+
+/// Draw the scrolling input (dim) and output (bright) scope.
+fn draw_scope(ui: &mut egui::Ui, scope: &Scope) {
+    let (response, painter) =
+        ui.allocate_painter(egui::vec2(ui.available_width(), 120.0), egui::Sense::hover());
+    let rect = response.rect;
+
+    let (input, output) = scope.snapshot();
+    let to_screen = |i: usize, v: f32| {
+        egui::pos2(
+            rect.left() + i as f32 / SCOPE_SIZE as f32 * rect.width(),
+            rect.center().y - v.clamp(-1.0, 1.0) * rect.height() / 2.0,
+        )
+    };
+
+    draw_line(
+        &painter,
+        input.iter().enumerate().map(|(i, &v)| to_screen(i, v)).collect(),
+        egui::Color32::DARK_GRAY,
+    );
+    draw_line(
+        &painter,
+        output.iter().enumerate().map(|(i, &v)| to_screen(i, v)).collect(),
+        egui::Color32::LIGHT_GREEN,
+    );
 }
 
 impl Default for Dontpanic {
     fn default() -> Self {
         Self {
             params: Arc::new(DontpanicParams::default()),
+            oversamplers: Vec::new(),
+            crossovers: Vec::new(),
+            sh_held: Vec::new(),
+            sh_phase: Vec::new(),
+            scope: Arc::new(Scope::default()),
+            held_notes: Vec::new(),
+            gate_env: Vec::new(),
+            gate_attack: 0.0,
+            gate_release: 0.0,
+            dry_delays: Vec::new(),
+            dc_blockers: Vec::new(),
         }
     }
 }
@@ -30,19 +743,90 @@ impl Default for Dontpanic {
 impl Default for DontpanicParams {
     fn default() -> Self {
         Self {
-            // This gain is stored as linear gain. NIH-plug comes with useful conversion functions
-            // to treat these kinds of parameters as if we were dealing with decibels. Storing this
-            // as decibels is easier to work with, but requires a conversion for every sample.
-            crush: FloatParam::new(
-                "Crush",
+            editor_state: EguiState::from_size(360, 420),
+
+            bits: FloatParam::new(
+                "Mid Crush",
                 0.0,
                 FloatRange::Linear {
                     min: 20.0,
                     max: 50.0,
                 },
             )
-            // Because the gain parameter is stored as linear gain instead of storing the value as
-            // decibels, we need logarithmic smoothing
+            .with_smoother(SmoothingStyle::Linear(50.0)),
+
+            crush_low: FloatParam::new(
+                "Low Crush",
+                0.0,
+                FloatRange::Linear {
+                    min: 20.0,
+                    max: 50.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0)),
+
+            crush_high: FloatParam::new(
+                "High Crush",
+                0.0,
+                FloatRange::Linear {
+                    min: 20.0,
+                    max: 50.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0)),
+
+            crossover_low: FloatParam::new(
+                "Low Crossover",
+                250.0,
+                FloatRange::Skewed {
+                    min: 40.0,
+                    max: 20_000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz")
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(0))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+
+            crossover_high: FloatParam::new(
+                "High Crossover",
+                2500.0,
+                FloatRange::Skewed {
+                    min: 40.0,
+                    max: 20_000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz")
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(0))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+
+            downsample: FloatParam::new(
+                "Downsample",
+                1.0,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 50.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0)),
+
+            oversampling: EnumParam::new("Oversampling", OversamplingFactor::X1),
+
+            midi_mode: EnumParam::new("MIDI Mode", MidiMode::Off),
+            key_track_invert: BoolParam::new("Key Track Invert", false),
+
+            shape: EnumParam::new("Shape", Waveshape::Tanh),
+            mix: FloatParam::new(
+                "Mix",
+                100.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 100.0,
+                },
+            )
+            .with_unit("%")
             .with_smoother(SmoothingStyle::Linear(50.0)),
         }
     }
@@ -71,7 +855,7 @@ impl Plugin for Dontpanic {
         names: PortNames::const_default(),
     }];
 
-    const MIDI_INPUT: MidiConfig = MidiConfig::None;
+    const MIDI_INPUT: MidiConfig = MidiConfig::Basic;
     const MIDI_OUTPUT: MidiConfig = MidiConfig::None;
 
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
@@ -91,46 +875,272 @@ impl Plugin for Dontpanic {
         self.params.clone()
     }
 
+    fn editor(&mut self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
+        let params = self.params.clone();
+        let scope = self.scope.clone();
+        create_egui_editor(
+            self.params.editor_state.clone(),
+            (),
+            |_, _| {},
+            move |egui_ctx, setter, _state| {
+                egui::CentralPanel::default().show(egui_ctx, |ui| {
+                    ui.heading("dontpanic");
+
+                    ui.label("Crush");
+                    ui.add(widgets::ParamSlider::for_param(&params.crush_low, setter));
+                    ui.add(widgets::ParamSlider::for_param(&params.bits, setter));
+                    ui.add(widgets::ParamSlider::for_param(&params.crush_high, setter));
+                    ui.add(widgets::ParamSlider::for_param(&params.downsample, setter));
+                    ui.add(widgets::ParamSlider::for_param(&params.oversampling, setter));
+                    ui.add(widgets::ParamSlider::for_param(&params.shape, setter));
+                    ui.add(widgets::ParamSlider::for_param(&params.mix, setter));
+
+                    ui.separator();
+                    ui.label("Crossovers");
+                    ui.add(widgets::ParamSlider::for_param(&params.crossover_low, setter));
+                    ui.add(widgets::ParamSlider::for_param(&params.crossover_high, setter));
+
+                    ui.separator();
+                    ui.label("MIDI");
+                    ui.add(widgets::ParamSlider::for_param(&params.midi_mode, setter));
+                    ui.add(widgets::ParamSlider::for_param(&params.key_track_invert, setter));
+
+                    ui.separator();
+                    ui.label("Transfer curve");
+                    draw_transfer_curve(ui, params.bits.value(), params.shape.value());
+
+                    ui.separator();
+                    ui.label("Scope");
+                    draw_scope(ui, &scope);
+                });
+            },
+        )
+    }
+
     fn initialize(
         &mut self,
-        _audio_io_layout: &AudioIOLayout,
-        _buffer_config: &BufferConfig,
-        _context: &mut impl InitContext<Self>,
+        audio_io_layout: &AudioIOLayout,
+        buffer_config: &BufferConfig,
+        context: &mut impl InitContext<Self>,
     ) -> bool {
         // Resize buffers and perform other potentially expensive initialization operations here.
-        // The `reset()` function is always called right after this function. You can remove this
-        // function if you do not need it.
+        // The `reset()` function is always called right after this function.
+        let num_channels = audio_io_layout
+            .main_output_channels
+            .map(NonZeroU32::get)
+            .unwrap_or(0) as usize;
+
+        // Build the oversampling prototype filters here (off the audio thread) rather than lazily
+        // on the first `process` call, and apply the restored `oversampling` value up front so
+        // `latency_samples` below is correct even before the first block.
+        oversampling_filters();
+        let factor = self.params.oversampling.value().factor();
+
+        self.oversamplers.clear();
+        self.oversamplers.resize_with(num_channels, || {
+            let mut bands = [Oversampler::new(), Oversampler::new(), Oversampler::new()];
+            for oversampler in &mut bands {
+                oversampler.set_factor(factor);
+            }
+            bands
+        });
+
+        // A session restored with `oversampling` already set to 2x/4x/8x should report the
+        // correct latency immediately, not just after the first processed block.
+        let latency = self
+            .oversamplers
+            .first()
+            .map(|bands| bands[0].latency_samples())
+            .unwrap_or(0);
+        context.set_latency_samples(latency as u32);
+
+        self.crossovers.clear();
+        self.crossovers.resize_with(num_channels, || Crossover {
+            sample_rate: buffer_config.sample_rate,
+            ..Crossover::default()
+        });
+
+        self.sh_held = vec![0.0; num_channels];
+        self.sh_phase = vec![0.0; num_channels];
+
+        // ~2 ms attack, ~20 ms release one-pole coefficients for the gate envelope.
+        self.gate_attack = one_pole_coeff(0.002, buffer_config.sample_rate);
+        self.gate_release = one_pole_coeff(0.020, buffer_config.sample_rate);
+        self.gate_env = vec![0.0; num_channels];
+        self.held_notes.clear();
+
+        self.dry_delays.clear();
+        self.dry_delays
+            .resize_with(num_channels, || DelayLine::new(MAX_DRY_DELAY));
+        self.dc_blockers.clear();
+        self.dc_blockers.resize_with(num_channels, DcBlocker::default);
+
         true
     }
 
+    fn reset(&mut self) {
+        for bands in &mut self.oversamplers {
+            for oversampler in bands {
+                oversampler.reset();
+            }
+        }
+        for crossover in &mut self.crossovers {
+            crossover.reset();
+        }
+        self.sh_held.iter_mut().for_each(|s| *s = 0.0);
+        self.sh_phase.iter_mut().for_each(|p| *p = 0.0);
+        self.gate_env.iter_mut().for_each(|e| *e = 0.0);
+        self.held_notes.clear();
+        for delay in &mut self.dry_delays {
+            delay.reset();
+        }
+        for dc in &mut self.dc_blockers {
+            dc.reset();
+        }
+    }
+
     fn process(
         &mut self,
         buffer: &mut Buffer,
         _aux: &mut AuxiliaryBuffers,
-        _context: &mut impl ProcessContext<Self>,
+        context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
-        for channel_samples in buffer.iter_samples() {
+        let midi_mode = self.params.midi_mode.value();
+        let key_track_invert = self.params.key_track_invert.value();
+
+        let factor = self.params.oversampling.value().factor();
+        for bands in &mut self.oversamplers {
+            for oversampler in bands {
+                oversampler.set_factor(factor);
+            }
+        }
+
+        // Report the oversampling latency so the host (and our dry path) can compensate.
+        let latency = self
+            .oversamplers
+            .first()
+            .map(|bands| bands[0].latency_samples())
+            .unwrap_or(0);
+        context.set_latency_samples(latency as u32);
+
+        let shape = self.params.shape.value();
+
+        let crossover_low = self.params.crossover_low.value();
+        let crossover_high = self.params.crossover_high.value();
+        for crossover in &mut self.crossovers {
+            crossover.set_frequencies(crossover_low, crossover_high);
+        }
+
+        let mut next_event = context.next_event();
+        for (sample_id, channel_samples) in buffer.iter_samples().enumerate() {
+            // Apply any MIDI events scheduled at or before this sample so note-on/off react at
+            // their sample-accurate position in the block, similar to how Buffr Glitch is
+            // MIDI-triggered, instead of retroactively affecting the whole buffer.
+            while let Some(event) = next_event {
+                if event.timing() > sample_id as u32 {
+                    break;
+                }
+
+                match event {
+                    NoteEvent::NoteOn { note, .. } => {
+                        self.held_notes.retain(|&n| n != note);
+                        self.held_notes.push(note);
+                    }
+                    NoteEvent::NoteOff { note, .. } => {
+                        self.held_notes.retain(|&n| n != note);
+                    }
+                    _ => (),
+                }
+
+                next_event = context.next_event();
+            }
+
+            // In key-track mode the newest held note scales the crush amounts.
+            let key_track_scale = match (midi_mode, self.held_notes.last()) {
+                (MidiMode::KeyTrack, Some(&note)) => {
+                    let ratio = 2.0f32.powf((note as f32 - 60.0) / 12.0);
+                    if key_track_invert {
+                        1.0 / ratio
+                    } else {
+                        ratio
+                    }
+                }
+                _ => 1.0,
+            };
+            // In gate mode the envelope chases 1.0 while any note is held, 0.0 otherwise.
+            let gate_target = if midi_mode == MidiMode::Gate && self.held_notes.is_empty() {
+                0.0
+            } else {
+                1.0
+            };
+
             // Smoothing is optionally built into the parameters themselves
-            let crush = self.params.crush.smoothed.next();
+            let bits = self.params.bits.smoothed.next();
+            let crush_low = self.params.crush_low.smoothed.next();
+            let crush_high = self.params.crush_high.smoothed.next();
+            let downsample = self.params.downsample.smoothed.next();
+            let mix = self.params.mix.smoothed.next() / 100.0;
 
             let mut channel = 0;
 
             for sample in channel_samples {
-                let mut channel_crush = crush;
-                // left
-                if channel == 0 {
-                    channel_crush *= 1.05;
+                let input = *sample;
+
+                // The left channel's crush amount is scaled up by 5% (a slightly finer grid), as it
+                // always has been.
+                let tilt = if channel == 0 { 1.05 } else { 1.0 };
+                let tilt = tilt * key_track_scale;
+                let band_crush = [crush_low * tilt, bits * tilt, crush_high * tilt];
+
+                // Latency-compensated dry copy for the mix.
+                let dry = self.dry_delays[channel].push_pop(input, latency);
+
+                // Split into bands, then crush each band with its own amount at the oversampled
+                // rate so the hard nonlinearities alias as little as possible, and sum back.
+                let bands = self.crossovers[channel].split(*sample);
+                let oversamplers = &mut self.oversamplers[channel];
+                let mut crushed = 0.0;
+                for band in 0..NUM_BANDS {
+                    let amount = band_crush[band];
+                    crushed += oversamplers[band]
+                        .process_sample(bands[band], |x| crush_shape(x, amount, shape));
                 }
 
-                let x = *sample;
-                let x = x * channel_crush;
-                let x = x.round();
-                let x = x / channel_crush;
+                // Only the rectifier/fold modes introduce DC; the others never had that problem, so
+                // leave them untouched rather than rolling off every mode's low end.
+                if matches!(shape, Waveshape::Rectify | Waveshape::AsymFold) {
+                    crushed = self.dc_blockers[channel].process(crushed);
+                }
+
+                // Sample-and-hold decimation: only latch a new value once the phase accumulator
+                // has advanced past a full sample period, otherwise repeat the held value.
+                self.sh_phase[channel] += 1.0 / downsample;
+                if self.sh_phase[channel] >= 1.0 {
+                    self.sh_phase[channel] -= 1.0;
+                    self.sh_held[channel] = crushed;
+                }
+                let mut out = self.sh_held[channel];
 
-                let x = x.abs();
-                let x = x.tanh();
+                // Gate mode: ramp the crushed signal in and out with the AR envelope so held
+                // notes open the gate and releases close it without clicking.
+                if midi_mode == MidiMode::Gate {
+                    let env = &mut self.gate_env[channel];
+                    let coeff = if gate_target > *env {
+                        self.gate_attack
+                    } else {
+                        self.gate_release
+                    };
+                    *env += (gate_target - *env) * coeff;
+                    out *= *env;
+                }
 
-                *sample = x;
+                // Crossfade the processed signal against the delay-compensated dry input.
+                *sample = dry * (1.0 - mix) + out * mix;
+
+                // Feed the left channel into the editor's scope.
+                if channel == 0 {
+                    self.scope.push(input, *sample);
+                }
 
                 channel += 1;
             }